@@ -1,6 +1,11 @@
 use dashmap::DashMap;
+use futures::{
+    future::{select_all, select_ok, BoxFuture},
+    stream::Stream,
+};
 use std::{sync::Arc, time::Duration};
-use tokio::time::timeout;
+use tokio::time::{timeout, timeout_at, Instant};
+use tokio_util::sync::CancellationToken;
 
 use crate::{error::AwaitStateError, watch_diff::WatchDiff};
 
@@ -42,7 +47,7 @@ impl<T: Clone + PartialEq> AwaitStateMap<T> {
             value.set(state).await;
             Ok(())
         } else {
-            Err(AwaitStateError::KeyNotFound)
+            Err(AwaitStateError::KeyNotFound(key.to_string()))
         }
     }
 
@@ -51,7 +56,7 @@ impl<T: Clone + PartialEq> AwaitStateMap<T> {
         if let Some(value) = self.map.get(key) {
             Ok(value.get_diff_cloned().await.1)
         } else {
-            Err(AwaitStateError::KeyNotFound)
+            Err(AwaitStateError::KeyNotFound(key.to_string()))
         }
     }
 
@@ -61,26 +66,204 @@ impl<T: Clone + PartialEq> AwaitStateMap<T> {
         F: Fn(&T, &T) -> bool + Send + Sync + 'static,
         T: Clone + PartialEq + Send + Sync + 'static,
     {
+        let entry = {
+            let entry = self
+                .map
+                .get(key)
+                .ok_or_else(|| AwaitStateError::KeyNotFound(key.to_string()))?;
+            Arc::clone(entry.value())
+        };
+
+        let (prev, curr, mut version) = entry.get_diff_versioned().await;
+        if let Some(prev) = prev.as_ref() {
+            if predicate(prev, &curr) {
+                return Ok(curr);
+            }
+        } else if predicate(&curr, &curr) {
+            return Ok(curr);
+        }
+
         loop {
-            let entry = self.map.get(key);
-            if let Some(entry) = entry {
-                let (prev, curr) = entry.get_diff_cloned().await;
-                if let Some(prev) = prev.as_ref() {
-                    if predicate(prev, &curr) {
-                        return Ok(curr);
-                    }
-                } else {
-                    if predicate(&curr, &curr) {
-                        return Ok(curr);
+            let (prev, curr, new_version) = entry.changed(version).await;
+            version = new_version;
+            if predicate(&prev, &curr) {
+                return Ok(curr);
+            }
+        }
+    }
+
+    /// Evaluates a predicate against the current `(prev, curr)` of a key exactly once, without
+    /// ever suspending. `Ok(Some(curr))` if already satisfied, `Ok(None)` if not yet, or
+    /// `AwaitStateError::WouldBlock` if the state is momentarily locked for writing
+    pub fn try_wait_until<F>(&self, key: &str, predicate: F) -> Result<Option<T>, AwaitStateError>
+    where
+        F: Fn(&T, &T) -> bool,
+    {
+        let (prev, curr) = self.try_get_diff(key)?;
+        let satisfied = match prev.as_ref() {
+            Some(prev) => predicate(prev, &curr),
+            None => predicate(&curr, &curr),
+        };
+        Ok(satisfied.then_some(curr))
+    }
+
+    /// Gets the current `(prev, curr)` pair for a key without ever suspending, or
+    /// `AwaitStateError::WouldBlock` if the state is momentarily locked for writing
+    pub fn try_get_diff(&self, key: &str) -> Result<(Option<T>, T), AwaitStateError> {
+        let value = self
+            .map
+            .get(key)
+            .ok_or_else(|| AwaitStateError::KeyNotFound(key.to_string()))?;
+        value.try_get_diff().ok_or(AwaitStateError::WouldBlock)
+    }
+
+    /// Waits until a given predicate holds for every key in `keys`, returning each key's
+    /// satisfying state in the same order as `keys`. Keys are read one at a time, so each
+    /// pass rechecks every already-satisfied key's version for stability before trusting the
+    /// batch; any key that moved mid-scan invalidates the pass and triggers a retry.
+    ///
+    /// Fails fast with `AwaitStateError::KeyNotFound` naming the first missing key if any
+    /// key in `keys` isn't present in the map.
+    pub async fn wait_all<F>(&self, keys: &[&str], predicate: F) -> Result<Vec<T>, AwaitStateError>
+    where
+        F: Fn(&T, &T) -> bool + Send + Sync + 'static,
+        T: Clone + PartialEq + Send + Sync + 'static,
+    {
+        let entries: Vec<Arc<WatchDiff<T>>> = keys
+            .iter()
+            .map(|key| {
+                self.map
+                    .get(*key)
+                    .map(|entry| Arc::clone(entry.value()))
+                    .ok_or_else(|| AwaitStateError::KeyNotFound(key.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        loop {
+            let mut values = Vec::with_capacity(entries.len());
+            let mut versions = Vec::with_capacity(entries.len());
+            let mut all_satisfied = true;
+
+            for entry in &entries {
+                let (prev, curr, version) = entry.get_diff_versioned().await;
+                versions.push(version);
+                let satisfied = match prev.as_ref() {
+                    Some(prev) => predicate(prev, &curr),
+                    None => predicate(&curr, &curr),
+                };
+                all_satisfied &= satisfied;
+                values.push(curr);
+            }
+
+            if all_satisfied {
+                let mut stable = true;
+                for (entry, &version) in entries.iter().zip(versions.iter()) {
+                    let (_, _, current_version) = entry.get_diff_versioned().await;
+                    if current_version != version {
+                        stable = false;
+                        break;
                     }
                 }
-                let (prev, curr) = entry.changed().await;
-                if predicate(&prev, &curr) {
-                    return Ok(curr);
+                if stable {
+                    return Ok(values);
                 }
-            } else {
-                return Err(AwaitStateError::KeyNotFound);
+                continue;
             }
+
+            // Not all keys agree yet: wait for any one of them to change,
+            // then re-check the whole snapshot from scratch.
+            let changes: Vec<BoxFuture<'static, ()>> = entries
+                .iter()
+                .zip(versions.iter())
+                .map(|(entry, &version)| {
+                    let entry = Arc::clone(entry);
+                    Box::pin(async move {
+                        entry.changed(version).await;
+                    }) as BoxFuture<'static, ()>
+                })
+                .collect();
+            select_all(changes).await;
+        }
+    }
+
+    /// Waits until a given predicate holds for any key in `keys`, returning
+    /// the key that fired along with its satisfying state.
+    ///
+    /// Fails fast with `AwaitStateError::KeyNotFound` naming the first
+    /// missing key if any key in `keys` isn't present in the map.
+    pub async fn wait_any<F>(
+        &self,
+        keys: &[&str],
+        predicate: F,
+    ) -> Result<(String, T), AwaitStateError>
+    where
+        F: Fn(&T, &T) -> bool + Send + Sync + 'static,
+        T: Clone + PartialEq + Send + Sync + 'static,
+    {
+        for key in keys {
+            if !self.map.contains_key(*key) {
+                return Err(AwaitStateError::KeyNotFound(key.to_string()));
+            }
+        }
+
+        let predicate = Arc::new(predicate);
+        let waits: Vec<BoxFuture<'_, Result<(String, T), AwaitStateError>>> = keys
+            .iter()
+            .map(|key| {
+                let key = key.to_string();
+                let predicate = Arc::clone(&predicate);
+                Box::pin(async move {
+                    let value = self
+                        .wait_until(&key, move |prev, curr| predicate(prev, curr))
+                        .await?;
+                    Ok((key, value))
+                }) as BoxFuture<'_, Result<(String, T), AwaitStateError>>
+            })
+            .collect();
+
+        let (result, _still_pending) = select_ok(waits).await?;
+        Ok(result)
+    }
+
+    /// Waits until a given predicate is true for some state, or returns
+    /// `AwaitStateError::Cancelled` if `token` fires first.
+    ///
+    /// Pass a child of a shared `CancellationToken` to let a single
+    /// `cancel()` tear down an entire group of outstanding waits at once,
+    /// e.g. on shutdown.
+    pub async fn wait_until_cancellable<F>(
+        &self,
+        key: &str,
+        predicate: F,
+        token: &CancellationToken,
+    ) -> Result<T, AwaitStateError>
+    where
+        F: Fn(&T, &T) -> bool + Send + Sync + 'static,
+        T: Clone + PartialEq + Send + Sync + 'static,
+    {
+        tokio::select! {
+            result = self.wait_until(key, predicate) => result,
+            _ = token.cancelled() => Err(AwaitStateError::Cancelled),
+        }
+    }
+
+    /// Subscribes to every future state transition of a key as a stream.
+    ///
+    /// Each item is a `(prev, current)` pair in the order it was applied.
+    /// Multiple subscribers can observe the same key independently, and a
+    /// subscriber that falls too far behind receives
+    /// `AwaitStateError::Lagged(n)` rather than missing updates silently.
+    pub fn subscribe(
+        &self,
+        key: &str,
+    ) -> Result<impl Stream<Item = Result<(Option<T>, T), AwaitStateError>>, AwaitStateError>
+    where
+        T: Send + 'static,
+    {
+        if let Some(value) = self.map.get(key) {
+            Ok(value.subscribe())
+        } else {
+            Err(AwaitStateError::KeyNotFound(key.to_string()))
         }
     }
 
@@ -99,4 +282,20 @@ impl<T: Clone + PartialEq> AwaitStateMap<T> {
             .await
             .map_err(|_| AwaitStateError::TimeoutExpired)?
     }
+
+    /// Waits until a given predicate is true for some state or until a fixed deadline passes
+    pub async fn wait_until_deadline<F>(
+        &self,
+        key: &str,
+        predicate: F,
+        deadline: Instant,
+    ) -> Result<T, AwaitStateError>
+    where
+        F: Fn(&T, &T) -> bool + Send + Sync + 'static,
+        T: Clone + PartialEq + Send + Sync + 'static,
+    {
+        timeout_at(deadline, self.wait_until(key, predicate))
+            .await
+            .map_err(|_| AwaitStateError::TimeoutExpired)?
+    }
 }
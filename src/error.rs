@@ -1,8 +1,17 @@
 #[derive(Debug, thiserror::Error)]
 pub enum AwaitStateError {
-    #[error("key not found")]
-    KeyNotFound,
+    #[error("key not found: {0}")]
+    KeyNotFound(String),
 
     #[error("timeout expired")]
     TimeoutExpired,
+
+    #[error("subscriber lagged behind by {0} transitions")]
+    Lagged(u64),
+
+    #[error("wait was cancelled")]
+    Cancelled,
+
+    #[error("state is currently locked for writing")]
+    WouldBlock,
 }
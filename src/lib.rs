@@ -61,7 +61,9 @@ pub use error::AwaitStateError;
 mod tests {
     use std::{sync::Arc, time::Duration};
 
-    use crate::await_state::AwaitStateMap;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{await_state::AwaitStateMap, error::AwaitStateError};
 
     #[derive(Clone, Debug, PartialEq)]
     enum DownloadState {
@@ -113,4 +115,318 @@ mod tests {
 
         assert!(res.is_err());
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_wait_until_does_not_miss_concurrent_set() {
+        // Regression guard for the lost-wakeup race: the setter fires as soon
+        // as the predicate is first evaluated (and found false), i.e. right
+        // in the window between the waiter's state read and its
+        // `notify.notified().await`. Without version-gating, the waiter can
+        // miss this wakeup entirely and hang until some unrelated future
+        // change; with it, it must observe the transition every time.
+        let map = Arc::new(AwaitStateMap::new());
+        map.put("download_1", DownloadState::NotStarted);
+
+        let ready = Arc::new(tokio::sync::Notify::new());
+
+        let map_move = Arc::clone(&map);
+        let ready_move = Arc::clone(&ready);
+        let setter = tokio::spawn(async move {
+            ready_move.notified().await;
+            map_move
+                .set_state("download_1", DownloadState::Finished)
+                .await
+                .unwrap();
+        });
+
+        let waiter = map.wait_until("download_1", move |_prev, curr| {
+            ready.notify_one();
+            *curr == DownloadState::Finished
+        });
+
+        let result = tokio::time::timeout(Duration::from_secs(2), waiter)
+            .await
+            .expect("wait_until missed the concurrent set and hung");
+        assert_eq!(result.unwrap(), DownloadState::Finished);
+
+        setter.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_streams_full_sequence_and_reports_lag() {
+        use futures::StreamExt;
+
+        let map = Arc::new(AwaitStateMap::new());
+        map.put("download_1", DownloadState::NotStarted);
+
+        let mut subscriber = map.subscribe("download_1").unwrap();
+
+        map.set_state("download_1", DownloadState::Started)
+            .await
+            .unwrap();
+        map.set_state("download_1", DownloadState::Finished)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            subscriber.next().await.unwrap().unwrap(),
+            (Some(DownloadState::NotStarted), DownloadState::Started)
+        );
+        assert_eq!(
+            subscriber.next().await.unwrap().unwrap(),
+            (Some(DownloadState::Started), DownloadState::Finished)
+        );
+
+        // Push more transitions than the broadcast channel's capacity
+        // without consuming them, so the next read reports a lag instead
+        // of silently dropping transitions.
+        for _ in 0..=crate::watch_diff::BROADCAST_CAPACITY {
+            map.set_state("download_1", DownloadState::Started)
+                .await
+                .unwrap();
+        }
+
+        match subscriber.next().await.unwrap() {
+            Err(AwaitStateError::Lagged(_)) => {}
+            other => panic!("expected Lagged, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_independent_subscribers_each_see_full_sequence() {
+        use futures::StreamExt;
+
+        let map = Arc::new(AwaitStateMap::new());
+        map.put("download_1", DownloadState::NotStarted);
+
+        let mut first = map.subscribe("download_1").unwrap();
+        let mut second = map.subscribe("download_1").unwrap();
+
+        map.set_state("download_1", DownloadState::Started)
+            .await
+            .unwrap();
+
+        let expected = (Some(DownloadState::NotStarted), DownloadState::Started);
+        assert_eq!(first.next().await.unwrap().unwrap(), expected);
+        assert_eq!(second.next().await.unwrap().unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_cancellable_returns_cancelled_when_token_fires() {
+        let map = Arc::new(AwaitStateMap::new());
+        map.put("download_1", DownloadState::NotStarted);
+
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let result = map
+            .wait_until_cancellable(
+                "download_1",
+                |_prev, curr| *curr == DownloadState::Finished,
+                &token,
+            )
+            .await;
+
+        assert!(matches!(result, Err(AwaitStateError::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_cancellable_resolves_normally_when_not_cancelled() {
+        let map = Arc::new(AwaitStateMap::new());
+        map.put("download_1", DownloadState::NotStarted);
+
+        let token = CancellationToken::new();
+
+        let map_move = Arc::clone(&map);
+        tokio::spawn(async move {
+            map_move
+                .set_state("download_1", DownloadState::Finished)
+                .await
+                .unwrap();
+        });
+
+        let result = map
+            .wait_until_cancellable(
+                "download_1",
+                |_prev, curr| *curr == DownloadState::Finished,
+                &token,
+            )
+            .await;
+
+        assert_eq!(result.unwrap(), DownloadState::Finished);
+    }
+
+    #[tokio::test]
+    async fn test_wait_all_resolves_once_every_key_is_satisfied() {
+        let map = Arc::new(AwaitStateMap::new());
+        map.put("a", DownloadState::NotStarted);
+        map.put("b", DownloadState::NotStarted);
+
+        let map_move = Arc::clone(&map);
+        tokio::spawn(async move {
+            map_move
+                .set_state("a", DownloadState::Finished)
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            map_move
+                .set_state("b", DownloadState::Finished)
+                .await
+                .unwrap();
+        });
+
+        let result = map
+            .wait_all(&["a", "b"], |_prev, curr| *curr == DownloadState::Finished)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result,
+            vec![DownloadState::Finished, DownloadState::Finished]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_all_names_first_missing_key() {
+        let map = Arc::new(AwaitStateMap::new());
+        map.put("a", DownloadState::NotStarted);
+
+        let result = map
+            .wait_all(&["missing_1", "missing_2"], |_prev, curr| {
+                *curr == DownloadState::Finished
+            })
+            .await;
+
+        match result {
+            Err(AwaitStateError::KeyNotFound(key)) => assert_eq!(key, "missing_1"),
+            other => panic!("expected KeyNotFound(\"missing_1\"), got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_any_resolves_with_the_key_that_fired() {
+        let map = Arc::new(AwaitStateMap::new());
+        map.put("a", DownloadState::NotStarted);
+        map.put("b", DownloadState::NotStarted);
+
+        let map_move = Arc::clone(&map);
+        tokio::spawn(async move {
+            map_move
+                .set_state("b", DownloadState::Finished)
+                .await
+                .unwrap();
+        });
+
+        let (key, value) = map
+            .wait_any(&["a", "b"], |_prev, curr| *curr == DownloadState::Finished)
+            .await
+            .unwrap();
+
+        assert_eq!(key, "b");
+        assert_eq!(value, DownloadState::Finished);
+    }
+
+    #[tokio::test]
+    async fn test_wait_any_names_first_missing_key() {
+        let map = Arc::new(AwaitStateMap::new());
+        map.put("a", DownloadState::NotStarted);
+
+        let result = map
+            .wait_any(&["missing_1", "missing_2"], |_prev, curr| {
+                *curr == DownloadState::Finished
+            })
+            .await;
+
+        match result {
+            Err(AwaitStateError::KeyNotFound(key)) => assert_eq!(key, "missing_1"),
+            other => panic!("expected KeyNotFound(\"missing_1\"), got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_try_wait_until_already_satisfied() {
+        let map = AwaitStateMap::new();
+        map.put("download_1", DownloadState::Finished);
+
+        let result = map
+            .try_wait_until("download_1", |_prev, curr| *curr == DownloadState::Finished)
+            .unwrap();
+
+        assert_eq!(result, Some(DownloadState::Finished));
+    }
+
+    #[test]
+    fn test_try_wait_until_not_yet_satisfied() {
+        let map = AwaitStateMap::new();
+        map.put("download_1", DownloadState::NotStarted);
+
+        let result = map
+            .try_wait_until("download_1", |_prev, curr| *curr == DownloadState::Finished)
+            .unwrap();
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_try_wait_until_missing_key() {
+        let map: AwaitStateMap<DownloadState> = AwaitStateMap::new();
+
+        let result =
+            map.try_wait_until("download_1", |_prev, curr| *curr == DownloadState::Finished);
+
+        match result {
+            Err(AwaitStateError::KeyNotFound(key)) => assert_eq!(key, "download_1"),
+            other => panic!("expected KeyNotFound(\"download_1\"), got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_try_get_diff_missing_key() {
+        let map: AwaitStateMap<DownloadState> = AwaitStateMap::new();
+
+        match map.try_get_diff("download_1") {
+            Err(AwaitStateError::KeyNotFound(key)) => assert_eq!(key, "download_1"),
+            other => panic!("expected KeyNotFound(\"download_1\"), got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_deadline_expires() {
+        let map = Arc::new(AwaitStateMap::new());
+        map.put("download_1", DownloadState::NotStarted);
+
+        let res = map
+            .wait_until_deadline(
+                "download_1",
+                |_prev, curr| *curr == DownloadState::Started,
+                tokio::time::Instant::now() + Duration::from_millis(100),
+            )
+            .await;
+
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_deadline_resolves_before_deadline() {
+        let map = Arc::new(AwaitStateMap::new());
+        map.put("download_1", DownloadState::NotStarted);
+
+        let map_move = Arc::clone(&map);
+        tokio::spawn(async move {
+            map_move
+                .set_state("download_1", DownloadState::Finished)
+                .await
+                .unwrap();
+        });
+
+        let result = map
+            .wait_until_deadline(
+                "download_1",
+                |_prev, curr| *curr == DownloadState::Finished,
+                tokio::time::Instant::now() + Duration::from_secs(2),
+            )
+            .await;
+
+        assert_eq!(result.unwrap(), DownloadState::Finished);
+    }
 }
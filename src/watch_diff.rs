@@ -1,15 +1,28 @@
-use tokio::sync::{Notify, RwLock};
+use futures::stream::{self, Stream};
+use tokio::sync::{broadcast, Notify, RwLock};
+
+use crate::error::AwaitStateError;
+
+/// Capacity of the broadcast channel backing [`WatchDiff::subscribe`].
+///
+/// Subscribers that fall more than this many transitions behind the
+/// publisher will observe a `Lagged` event instead of silently missing
+/// updates.
+pub(crate) const BROADCAST_CAPACITY: usize = 128;
 
 /// A struct to store previous and current state
 struct Inner<T> {
     prev: Option<T>,
     current: T,
+    /// Monotonically increasing version, bumped on every `set`
+    version: u64,
 }
 
 /// A struct to store the inner state and Tokio notify
 pub struct WatchDiff<T> {
     inner: RwLock<Inner<T>>,
     notify: Notify,
+    sender: broadcast::Sender<(Option<T>, T)>,
 }
 
 impl<T: Clone + PartialEq> WatchDiff<T> {
@@ -18,10 +31,13 @@ impl<T: Clone + PartialEq> WatchDiff<T> {
         let inner = Inner {
             prev: None,
             current: initial,
+            version: 0,
         };
+        let (sender, _) = broadcast::channel(BROADCAST_CAPACITY);
         let watch_diff = WatchDiff {
             inner: RwLock::new(inner),
             notify: Notify::new(),
+            sender,
         };
         watch_diff
     }
@@ -32,25 +48,72 @@ impl<T: Clone + PartialEq> WatchDiff<T> {
         let current = write.current.clone();
         write.prev = Some(current);
         write.current = new;
+        write.version += 1;
+        // Ignore send errors: they only mean there are currently no subscribers.
+        let _ = self
+            .sender
+            .send((write.prev.clone(), write.current.clone()));
         self.notify.notify_waiters();
     }
 
+    /// Subscribes to every future `(prev, current)` transition as a stream.
+    ///
+    /// Unlike `changed`, which only resolves a single await, this yields
+    /// every transition published after subscribing. A subscriber that
+    /// falls behind the broadcast channel's capacity receives
+    /// `AwaitStateError::Lagged(n)` for the `n` missed transitions instead
+    /// of silently skipping them.
+    pub fn subscribe(&self) -> impl Stream<Item = Result<(Option<T>, T), AwaitStateError>>
+    where
+        T: Send + 'static,
+    {
+        let receiver = self.sender.subscribe();
+        stream::unfold(receiver, |mut receiver| async move {
+            match receiver.recv().await {
+                Ok(diff) => Some((Ok(diff), receiver)),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    Some((Err(AwaitStateError::Lagged(n)), receiver))
+                }
+                Err(broadcast::error::RecvError::Closed) => None,
+            }
+        })
+    }
+
     /// Get the past and current state with cloning
     pub async fn get_diff_cloned(&self) -> (Option<T>, T) {
         let read = self.inner.read().await;
         (read.prev.clone(), read.current.clone())
     }
 
-    /// Check if the state has changed and return it if so.
-    pub async fn changed(&self) -> (T, T) {
+    /// Reads the current diff without ever suspending; `None` if the lock is held for writing
+    pub fn try_get_diff(&self) -> Option<(Option<T>, T)> {
+        let read = self.inner.try_read().ok()?;
+        Some((read.prev.clone(), read.current.clone()))
+    }
+
+    /// Get the past and current state along with the version they were read at.
+    pub async fn get_diff_versioned(&self) -> (Option<T>, T, u64) {
+        let read = self.inner.read().await;
+        (read.prev.clone(), read.current.clone(), read.version)
+    }
+
+    /// Waits for the first transition after `last_seen`, acquiring the notify before re-reading state so a concurrent `set` can't be missed
+    pub async fn changed(&self, last_seen: u64) -> (T, T, u64) {
+        let mut last_seen = last_seen;
         loop {
-            self.notify.notified().await;
-            let (prev, curr) = self.get_diff_cloned().await;
-            if let Some(prev) = prev {
-                if curr != prev {
-                    return (prev, curr);
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+
+            let (prev, curr, version) = self.get_diff_versioned().await;
+            if version != last_seen {
+                if let Some(prev) = prev {
+                    return (prev, curr, version);
                 }
+                last_seen = version;
+                continue;
             }
+
+            notified.await;
         }
     }
 }